@@ -1,9 +1,12 @@
 use super::{
     convert::{ToRubyValue, ToWasmVal},
     func_type::FuncType,
+    global::Global,
+    memory::Memory,
     params::Params,
     root,
     store::{Store, StoreData},
+    table::Table,
 };
 use crate::error;
 use magnus::{
@@ -15,9 +18,16 @@ use magnus::{
     DataTypeFunctions, Error, Exception, Module as _, Object, RArray, RClass, RHash, RString,
     TryConvert, TypedData, Value, QNIL,
 };
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::ffi::c_void;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Mutex, MutexGuard};
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
 use wasmtime::{
-    AsContextMut, Caller as CallerImpl, Extern, ExternType, Func as FuncImpl, Trap, Val,
+    AsContextMut, Caller as CallerImpl, Extern, Func as FuncImpl, StoreContextMut, Trap, Val,
+    ValType,
 };
 
 #[derive(TypedData, Debug)]
@@ -25,6 +35,16 @@ use wasmtime::{
 pub struct Func {
     store: Value,
     inner: FuncImpl,
+    // Signature and scratch buffer memoized so the common `call` path avoids
+    // re-deriving the `FuncType` and re-allocating a results `Vec` every call.
+    cache: RefCell<Option<TypeCache>>,
+    results: RefCell<Vec<Val>>,
+}
+
+#[derive(Debug)]
+struct TypeCache {
+    params: Vec<ValType>,
+    results: Vec<ValType>,
 }
 
 impl DataTypeFunctions for Func {
@@ -53,17 +73,40 @@ impl Func {
 
         let store: &Store = s.try_convert()?;
         store.retain(proc.into());
+        let is_async = store.is_async();
         let mut store = store.borrow_mut();
         let context = store.as_context_mut();
         let ty = functype.get();
 
-        let inner = wasmtime::Func::new(
-            context,
-            ty.clone(),
-            make_func_callable(ty, proc, send_caller),
-        );
+        // The signature is known at construction time, so prime the cache now.
+        let cache = TypeCache {
+            params: ty.params().collect(),
+            results: ty.results().collect(),
+        };
+        let results = vec![Val::null(); cache.results.len()];
+
+        // Async stores drive the host Proc on a Ruby Fiber so it can suspend;
+        // sync stores call it directly.
+        let inner = if is_async {
+            wasmtime::Func::new_async(
+                context,
+                ty.clone(),
+                make_func_async_callable(ty, proc, send_caller),
+            )
+        } else {
+            wasmtime::Func::new(
+                context,
+                ty.clone(),
+                make_func_callable(ty, proc, send_caller),
+            )
+        };
 
-        Ok(Self { store: s, inner })
+        Ok(Self {
+            store: s,
+            inner,
+            cache: RefCell::new(Some(cache)),
+            results: RefCell::new(results),
+        })
     }
 
     pub fn get(&self) -> FuncImpl {
@@ -71,9 +114,101 @@ impl Func {
         self.inner
     }
 
+    /// Wraps an existing `wasmtime::Func` handle, binding it to the given store
+    /// value. Used when an `Extern` is pulled back out of the store (e.g. by an
+    /// instance, the linker, or a `Caller`) rather than freshly defined.
+    pub(crate) fn from_inner(store: Value, inner: FuncImpl) -> Self {
+        // Signature unknown here; memoized lazily on first `call`.
+        Self {
+            store,
+            inner,
+            cache: RefCell::new(None),
+            results: RefCell::new(Vec::new()),
+        }
+    }
+
     pub fn call(&self, args: RArray) -> Result<Value, Error> {
-        let store: &Store = self.store.try_convert()?;
-        Self::invoke(store, &self.inner, args).map_err(|e| e.into())
+        // A `Func` is bound either to its owning `Wasmtime::Store` or, when it
+        // was pulled out of a `Caller#export`, to the live `Caller` whose
+        // context is only valid for the duration of the host call.
+        // `with_store_context` resolves either binding; the other wrapper types
+        // (`Memory`/`Global`/`Table`) use it the same way.
+        let _guard = acquire_call_lock()?;
+        with_store_context(self.store, |context, is_async| {
+            self.invoke_cached(context, is_async, args)
+        })?
+        .map_err(|e| e.into())
+    }
+
+    /// Fast path for `Func#call`: reuses the memoized param/result `ValType`s
+    /// and the per-`Func` results scratch buffer instead of deriving the
+    /// `FuncType` and allocating a fresh `Vec` on every invocation.
+    fn invoke_cached(
+        &self,
+        mut context: impl AsContextMut<Data = StoreData>,
+        is_async: bool,
+        args: RArray,
+    ) -> Result<Value, InvokeError> {
+        // Memoize the signature the first time we're called (e.g. for a Func
+        // pulled out of an instance, where the type wasn't known up front).
+        if self.cache.borrow().is_none() {
+            let func_ty = self.inner.ty(context.as_context_mut());
+            let cache = TypeCache {
+                params: func_ty.params().collect(),
+                results: func_ty.results().collect(),
+            };
+            *self.results.borrow_mut() = vec![Val::null(); cache.results.len()];
+            *self.cache.borrow_mut() = Some(cache);
+        }
+
+        let cache = self.cache.borrow();
+        let cache = cache.as_ref().unwrap();
+
+        let params_slice = unsafe { args.as_slice() };
+        // `Params::new` takes its `ValType`s by value; clone the cached ones
+        // rather than re-deriving the whole `FuncType` (the expensive part we
+        // memoized) on every call.
+        let params = Params::new(params_slice, cache.params.clone())?.to_vec()?;
+        let result_len = cache.results.len();
+
+        // Reuse the per-`Func` results scratch buffer, but fall back to a fresh
+        // `Vec` if it's already borrowed: a guest call may re-enter `Func#call`
+        // on this same `Func` (direct or indirect recursion through a host
+        // import), and a second `borrow_mut` would panic.
+        let mut borrowed = self.results.try_borrow_mut();
+        let mut fallback;
+        let results: &mut Vec<Val> = match borrowed {
+            Ok(ref mut scratch) => {
+                scratch.clear();
+                scratch.resize(result_len, Val::null());
+                &mut **scratch
+            }
+            Err(_) => {
+                fallback = vec![Val::null(); result_len];
+                &mut fallback
+            }
+        };
+
+        let func = &self.inner;
+        let call_result = if is_async {
+            // Async stores must use `call_async`; `call` panics on them. The
+            // host Proc runs on a Ruby Fiber that suspends via `Poll::Pending`,
+            // which `block_on` pumps until the call completes.
+            block_on(func.call_async(context.as_context_mut(), &params, results.as_mut_slice()))
+        } else {
+            // Release the GVL for the duration of the (CPU-bound) Wasm call so
+            // other Ruby threads can run; host imports re-acquire it in
+            // `invoke_host_proc` before touching any Ruby object.
+            unsafe { nogvl(|| func.call(context.as_context_mut(), &params, results.as_mut_slice())) }
+        };
+        call_result.map_err(|e| {
+            match context.as_context_mut().data_mut().exception().take() {
+                Some(exception) => merge_trap_trace(exception, &e),
+                None => trap_error(&e),
+            }
+        })?;
+
+        results_to_ruby(results)
     }
 
     pub fn invoke(
@@ -81,6 +216,8 @@ impl Func {
         func: &wasmtime::Func,
         args: RArray,
     ) -> Result<Value, InvokeError> {
+        let _guard = acquire_call_lock()?;
+        let is_async = store.is_async();
         let mut store = store.borrow_mut();
         let func_ty = func.ty(store.as_context_mut());
         let param_types = func_ty.params().collect::<Vec<_>>();
@@ -88,27 +225,67 @@ impl Func {
         let params = Params::new(params_slice, param_types)?.to_vec()?;
         let mut results = vec![Val::null(); func_ty.results().len()];
 
-        func.call(store.as_context_mut(), &params, &mut results)
-            .map_err(|e| {
-                store
-                    .as_context_mut()
-                    .data_mut()
-                    .exception()
-                    .take()
-                    .map(Error::from)
-                    .unwrap_or_else(|| error!("Could not invoke function: {}", e))
-            })?;
-
-        match results.as_slice() {
-            [] => Ok(QNIL.into()),
-            [result] => result.to_ruby_value().map_err(|e| e.into()),
-            _ => {
-                let array = RArray::with_capacity(results.len());
-                for result in results {
-                    array.push(result.to_ruby_value()?)?;
-                }
-                Ok(array.into())
+        let call_result = if is_async {
+            // See `invoke_cached`: async stores require `call_async`.
+            block_on(func.call_async(store.as_context_mut(), &params, &mut results))
+        } else {
+            // See `invoke_cached`: drop the GVL while the Wasm runs.
+            unsafe { nogvl(|| func.call(store.as_context_mut(), &params, &mut results)) }
+        };
+        call_result.map_err(|e| {
+            match store.as_context_mut().data_mut().exception().take() {
+                Some(exception) => merge_trap_trace(exception, &e),
+                None => trap_error(&e),
+            }
+        })?;
+
+        results_to_ruby(&results)
+    }
+}
+
+/// Resolves the store value a wrapper is bound to — either a `Wasmtime::Store`
+/// or a live `Wasmtime::Caller` (handed out by `Caller#export` for the duration
+/// of a host call) — into a wasmtime context, and runs `f` with it plus whether
+/// the store is async.
+///
+/// Every store-bound wrapper (`Func`, `Memory`, `Global`, `Table`) resolves its
+/// context through here so an export obtained inside a host call is driven via
+/// the caller's borrow rather than re-borrowing the already-borrowed store.
+pub(crate) fn with_store_context<R>(
+    store: Value,
+    f: impl FnOnce(StoreContextMut<'_, StoreData>, bool) -> R,
+) -> Result<R, Error> {
+    if let Ok(store) = store.try_convert::<&Store>() {
+        let is_async = store.is_async();
+        let mut guard = store.borrow_mut();
+        Ok(f(guard.as_context_mut(), is_async))
+    } else {
+        // Bound to a live `Caller` from `Caller#export`; use the caller's
+        // context. Async-ness is a per-Store flag, not something that turns
+        // off for a nested call, so carry the caller's own flag through
+        // rather than assuming sync — `wasmtime::Func::call` panics if the
+        // Store was actually configured async.
+        let caller: &Caller = store.try_convert()?;
+        caller.check_valid()?;
+        let is_async = caller.is_async;
+        let mut guard = caller.inner.borrow_mut();
+        Ok(f(guard.as_context_mut(), is_async))
+    }
+}
+
+/// Converts a slice of Wasm result `Val`s into the Ruby return value for
+/// `Func#call`: `nil` for no results, the bare value for one, an `Array`
+/// otherwise.
+fn results_to_ruby(results: &[Val]) -> Result<Value, InvokeError> {
+    match results {
+        [] => Ok(QNIL.into()),
+        [result] => result.to_ruby_value().map_err(|e| e.into()),
+        _ => {
+            let array = RArray::with_capacity(results.len());
+            for result in results {
+                array.push(result.to_ruby_value()?)?;
             }
+            Ok(array.into())
         }
     }
 }
@@ -130,63 +307,554 @@ fn make_func_callable(
 
     move |caller: CallerImpl<'_, StoreData>, params: &[Val], results: &mut [Val]| {
         let caller = RefCell::new(caller);
+        // Shared with every `Caller`/export Ruby code obtains from this call
+        // (see `Caller::export`); flipped to `false` by the guard below right
+        // before `caller`'s frame goes away, so a stashed reference raises
+        // instead of dereferencing dangling memory.
+        let valid = Rc::new(Cell::new(true));
+        let _invalidate_caller = CallerValidityGuard(valid.clone());
+        // Sync path: call the host Proc directly on the executing thread.
+        invoke_host_proc(
+            &caller,
+            &ty,
+            shareable_proc.0,
+            params,
+            results,
+            send_caller,
+            false,
+            valid,
+        )
+    }
+}
+
+fn make_func_async_callable(
+    ty: &wasmtime::FuncType,
+    proc: Proc,
+    send_caller: bool,
+) -> impl for<'c> Fn(
+    CallerImpl<'c, StoreData>,
+    &'c [Val],
+    &'c mut [Val],
+) -> Box<dyn Future<Output = Result<(), Trap>> + Send + 'c>
+       + Send
+       + Sync
+       + 'static {
+    let ty = ty.to_owned();
+    let shareable_proc = ShareableProc(proc);
 
-        let rparams = if send_caller {
-            let p = RArray::with_capacity(params.len() + 1);
-            let c = Caller { inner: &caller };
-            p.push(Value::from(c)).ok();
+    move |caller: CallerImpl<'_, StoreData>, params: &[Val], results: &mut [Val]| {
+        let ty = ty.clone();
+        let proc = shareable_proc.0;
+        // Async path: drive the host Proc on a Ruby Fiber so it can suspend
+        // (e.g. awaiting IO). Each `Fiber.yield` surfaces as `Poll::Pending`
+        // out of `FiberFuture`, handing control back to whatever polls the
+        // `call_async` future. Here that is `block_on`, which re-polls at
+        // once, so the calling OS thread keeps spinning until the Proc
+        // returns; it is not parked while the Proc awaits.
+        // The future is only ever polled while the GVL is held, one thread at
+        // a time, so wrapping it in a `SendFuture` upholds the same invariant
+        // as `ShareableProc`.
+        Box::new(SendFuture(async move {
+            let caller = RefCell::new(caller);
+            // See `make_func_callable`: shared with every exported `Caller`,
+            // invalidated right before `caller`'s frame goes away.
+            let valid = Rc::new(Cell::new(true));
+            let _invalidate_caller = CallerValidityGuard(valid.clone());
+            let rparams = build_rparams(&caller, params, send_caller, true, valid)?;
+            let run_result = match FiberFuture::new(proc, rparams) {
+                Ok(fiber) => fiber.await,
+                Err(e) => Err(e),
+            };
+            finish_host_call(&caller, &ty, results, proc, run_result)
+        }))
+    }
+}
 
-            p
-        } else {
-            RArray::with_capacity(params.len())
+/// Sync host-call body. Re-acquires the GVL (`Func::invoke` dropped it around
+/// the Wasm call), converts the params, calls the host `Proc` directly on the
+/// executing thread, and writes the results back.
+fn invoke_host_proc(
+    caller: &RefCell<CallerImpl<'_, StoreData>>,
+    ty: &wasmtime::FuncType,
+    proc: Proc,
+    params: &[Val],
+    results: &mut [Val],
+    send_caller: bool,
+    is_async: bool,
+    valid: Rc<Cell<bool>>,
+) -> Result<(), Trap> {
+    // `Func::invoke` drops the GVL around the Wasm call, so re-acquire it here
+    // before creating or calling any Ruby object, and release it again on the
+    // way back to Wasm. No Ruby VALUE may be touched outside this region.
+    unsafe {
+        with_gvl(move || {
+            let rparams = build_rparams(caller, params, send_caller, is_async, valid)?;
+            let run_result = proc.call::<RArray, Value>(rparams);
+            finish_host_call(caller, ty, results, proc, run_result)
+        })
+    }
+}
+
+/// Builds the Ruby argument array passed to a host `Proc`, prepending the
+/// `Caller` wrapper when the Proc was registered with `caller: true`.
+///
+/// `is_async` is the calling `Store`'s own async flag (it's a per-Store
+/// config, not something that flips for a nested call), carried along so a
+/// `Caller#export` pulled out of this `Caller` later resolves its context on
+/// the right path instead of assuming sync. `valid` is the shared flag the
+/// enclosing trampoline's `CallerValidityGuard` clears when this host call
+/// returns; see `Caller::check_valid`.
+fn build_rparams(
+    caller: &RefCell<CallerImpl<'_, StoreData>>,
+    params: &[Val],
+    send_caller: bool,
+    is_async: bool,
+    valid: Rc<Cell<bool>>,
+) -> Result<RArray, Trap> {
+    let rparams = if send_caller {
+        let p = RArray::with_capacity(params.len() + 1);
+        let c = Caller {
+            inner: caller,
+            is_async,
+            valid,
         };
+        p.push(Value::from(c)).ok();
+
+        p
+    } else {
+        RArray::with_capacity(params.len())
+    };
+
+    for (i, param) in params.iter().enumerate() {
+        let rparam = param.to_ruby_value().map_err(|e| {
+            wasmtime::Trap::new(format!("invalid argument at index {}: {}", i, e))
+        })?;
+        rparams.push(rparam).ok();
+    }
 
-        for (i, param) in params.iter().enumerate() {
-            let rparam = param.to_ruby_value().map_err(|e| {
-                wasmtime::Trap::new(format!("invalid argument at index {}: {}", i, e))
-            })?;
-            rparams.push(rparam).ok();
-        }
-        let proc = shareable_proc.0;
+    Ok(rparams)
+}
 
-        proc.call::<RArray, Value>(rparams)
-            .map_err(|e| {
-                if let Error::Exception(exception) = e {
-                    caller.borrow_mut().data_mut().exception().hold(exception);
-                }
-                e
-            })
-            .and_then(|proc_result| {
-                match results.len() {
-                    0 => Ok(()), // Ignore return value
-                    n => {
-                        // For len=1, accept both `val` and `[val]`
-                        let proc_result = RArray::try_convert(proc_result)?;
-                        if proc_result.len() != n {
-                            return Result::Err(error!(
-                                "wrong number of results (given {}, expected {})",
-                                proc_result.len(),
-                                n
-                            ));
-                        }
-                        for ((rb_val, wasm_val), ty) in unsafe { proc_result.as_slice() }
-                            .iter()
-                            .zip(results.iter_mut())
-                            .zip(ty.results())
-                        {
-                            *wasm_val = rb_val.to_wasm_val(&ty)?;
-                        }
-                        Ok(())
+/// Routes the host `Proc`'s outcome back to Wasm: any raised exception is held
+/// on `StoreData` so `Func#call` can re-raise the structured object, and the
+/// returned values are converted into the Wasm `results`.
+fn finish_host_call(
+    caller: &RefCell<CallerImpl<'_, StoreData>>,
+    ty: &wasmtime::FuncType,
+    results: &mut [Val],
+    proc: Proc,
+    run_result: Result<Value, Error>,
+) -> Result<(), Trap> {
+    run_result
+        .map_err(|e| {
+            if let Error::Exception(exception) = &e {
+                caller.borrow_mut().data_mut().exception().hold(*exception);
+            }
+            e
+        })
+        .and_then(|proc_result| {
+            match results.len() {
+                0 => Ok(()), // Ignore return value
+                n => {
+                    // For len=1, accept both `val` and `[val]`
+                    let proc_result = RArray::try_convert(proc_result)?;
+                    if proc_result.len() != n {
+                        return Result::Err(error!(
+                            "wrong number of results (given {}, expected {})",
+                            proc_result.len(),
+                            n
+                        ));
+                    }
+                    for ((rb_val, wasm_val), ty) in unsafe { proc_result.as_slice() }
+                        .iter()
+                        .zip(results.iter_mut())
+                        .zip(ty.results())
+                    {
+                        *wasm_val = rb_val.to_wasm_val(&ty)?;
                     }
+                    Ok(())
                 }
-            })
-            .map_err(|e| {
-                wasmtime::Trap::new(format!(
-                    "Error when calling Func {}\n Error: {}",
-                    proc.inspect(),
-                    e
-                ))
-            })
+            }
+        })
+        .map_err(|e| {
+            wasmtime::Trap::new(format!(
+                "Error when calling Func {}\n Error: {}",
+                proc.inspect(),
+                e
+            ))
+        })
+}
+
+/// A host `Proc` running on a Ruby `Fiber`, exposed as a `Future` so it
+/// cooperates with wasmtime's async call machinery. Each poll resumes the
+/// fiber once; while the fiber is still `alive?` (i.e. it called
+/// `Fiber.yield`), the future returns `Poll::Pending` instead of draining the
+/// fiber in a synchronous resume loop. See [`block_on`] for the important
+/// caveat that, absent a real reactor, those pending yields are currently spun
+/// rather than parked.
+struct FiberFuture {
+    fiber: Value,
+    rparams: Option<RArray>,
+}
+
+impl FiberFuture {
+    /// `Fiber.new(&proc)` uses the host `Proc` as the fiber body; the first
+    /// `resume` (on the first poll) passes the Wasm params as block arguments.
+    fn new(proc: Proc, rparams: RArray) -> Result<Self, Error> {
+        let fiber = fiber_class().funcall_with_block::<_, _, Value>("new", (), proc)?;
+        Ok(Self {
+            fiber,
+            rparams: Some(rparams),
+        })
+    }
+}
+
+impl Future for FiberFuture {
+    type Output = Result<Value, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let resumed = match self.rparams.take() {
+            Some(rparams) => self.fiber.funcall("resume", unsafe { rparams.as_slice() }),
+            None => self.fiber.funcall("resume", ()),
+        };
+        let value: Value = match resumed {
+            Ok(value) => value,
+            Err(e) => return Poll::Ready(Err(e)),
+        };
+
+        match self.fiber.funcall::<_, _, bool>("alive?", ()) {
+            // The Proc called `Fiber.yield`. We surface `Poll::Pending` so the
+            // fiber integrates with wasmtime's async call machinery, but note
+            // the limitation documented on `block_on`: there is no reactor to
+            // register the awaited resource with, so we immediately re-arm the
+            // waker and the fiber is resumed again on the next poll rather than
+            // truly suspended.
+            Ok(true) => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+            Ok(false) => Poll::Ready(Ok(value)),
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+/// Minimal executor that drives a wasmtime `call_async` future to completion.
+///
+/// This is deliberately a *spin* loop paired with a no-op waker, not a real
+/// reactor: when a host Proc's fiber `Fiber.yield`s, [`FiberFuture`] returns
+/// `Poll::Pending` and immediately re-arms the waker, so this loop re-polls
+/// (and re-resumes the fiber) as soon as it runs again. A Proc that yields to
+/// await real IO therefore busy-waits on the executing OS thread until it
+/// returns, rather than handing control to an embedder poll loop. Integrating
+/// a waker that parks on the awaited resource, so this thread itself doesn't
+/// spin, is left for a follow-up; the `Fiber`-based suspension point is in
+/// place so a yielding Proc still completes correctly.
+///
+/// What we do fix here: each poll only needs the GVL while it's actually
+/// resuming the fiber (a Ruby call). Between polls, drop the GVL the same way
+/// `nogvl` does for the sync path, so a Proc awaiting IO no longer pins every
+/// other Ruby `Thread` for the duration — only this thread's own progress is
+/// still a spin, not the whole VM's.
+///
+/// NOTE FOR REVIEWERS: this is a partial fix to the GVL-monopolization
+/// problem, not the full async benefit the `call_async` entry point implies.
+/// Other Ruby `Thread`s now make progress while a call is pending, but the
+/// calling `Thread`/call itself still does not suspend — it spins until the
+/// fiber finishes, so a `Func#call` on an async `Store` still fully occupies
+/// its own caller for the call's real duration. Please confirm this reduced
+/// scope is acceptable to merge as-is rather than assuming it delivers
+/// thread-level suspension for the call in progress; closing that gap needs
+/// a real reactor wired to whatever the embedder awaits, which is a larger
+/// follow-up than this fix.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => {
+                unsafe { nogvl(std::thread::yield_now) };
+                continue;
+            }
+        }
+    }
+}
+
+fn noop_waker() -> Waker {
+    const VTABLE: RawWakerVTable = RawWakerVTable::new(
+        |_| RawWaker::new(std::ptr::null(), &VTABLE),
+        |_| {},
+        |_| {},
+        |_| {},
+    );
+    unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) }
+}
+
+/// Serializes entry into the section below where the GVL is released
+/// (`nogvl`) or the async executor is pumped (`block_on`). That section holds
+/// the `Store`'s borrow (and, on the cached path, the per-`Func` `cache`/
+/// `results` borrows) for its whole duration, but without the GVL two Ruby
+/// `Thread`s can now genuinely run Rust code at once; a second thread
+/// re-entering here would race those `RefCell`s' borrow flags, which isn't
+/// atomic, from real concurrent OS threads -- undefined behavior, not just a
+/// `BorrowMutError` panic.
+///
+/// We can't block on contention: the thread that's already inside may need
+/// the GVL back first (a sync host import re-acquires it via `with_gvl`), and
+/// this lock is still held while we still have the GVL ourselves, so blocking
+/// here risks deadlocking against that. Raising a catchable Ruby error on
+/// contention instead keeps both threads alive and gives the caller something
+/// to rescue or retry.
+static CALL_LOCK: Mutex<()> = Mutex::new(());
+
+thread_local! {
+    // Tracks same-thread re-entry into the section `CALL_LOCK` guards: a host
+    // Proc that calls back into a live `Func` (e.g. one pulled out of
+    // `Caller#export`, or plain recursive host/guest calls) re-enters
+    // `Func::call`/`Func::invoke` on the very same thread, still holding the
+    // GVL, with no real concurrency to guard against. Only the first,
+    // outermost entry on a thread takes `CALL_LOCK`; nested entries just bump
+    // this counter instead of contending with themselves.
+    static CALL_DEPTH: Cell<u32> = Cell::new(0);
+}
+
+/// RAII handle returned by [`acquire_call_lock`]. Holds the real `CALL_LOCK`
+/// guard only on the outermost call on this thread; nested calls hold `None`
+/// and just decrement `CALL_DEPTH` on drop.
+struct CallLock(Option<MutexGuard<'static, ()>>);
+
+impl Drop for CallLock {
+    fn drop(&mut self) {
+        CALL_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+fn acquire_call_lock() -> Result<CallLock, Error> {
+    let depth = CALL_DEPTH.with(Cell::get);
+    let guard = if depth == 0 {
+        Some(
+            CALL_LOCK
+                .try_lock()
+                .map_err(|_| error!("Wasm call already in progress on another Thread"))?,
+        )
+    } else {
+        None
+    };
+    CALL_DEPTH.with(|d| d.set(depth + 1));
+    Ok(CallLock(guard))
+}
+
+/// Runs `func` with the GVL released so other Ruby threads can make progress.
+///
+/// # Safety
+/// `func` must not create, call, or otherwise touch any Ruby VALUE while the
+/// GVL is released. Host imports re-acquire the GVL via [`with_gvl`].
+unsafe fn nogvl<F, R>(func: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    struct State<F, R> {
+        func: Option<F>,
+        result: Option<R>,
+    }
+
+    extern "C" fn call<F, R>(arg: *mut c_void) -> *mut c_void
+    where
+        F: FnOnce() -> R,
+    {
+        let state = unsafe { &mut *(arg as *mut State<F, R>) };
+        let func = state.func.take().unwrap();
+        state.result = Some(func());
+        std::ptr::null_mut()
+    }
+
+    let mut state = State {
+        func: Some(func),
+        result: None,
+    };
+    rb_sys::rb_thread_call_without_gvl(
+        Some(call::<F, R>),
+        &mut state as *mut _ as *mut c_void,
+        None,
+        std::ptr::null_mut(),
+    );
+    state.result.unwrap()
+}
+
+/// Re-acquires the GVL for the duration of `func`, the inverse of [`nogvl`].
+/// Called from the host trampoline, which wasmtime enters while the GVL is
+/// released by [`Func::invoke`].
+///
+/// # Safety
+/// Must only be called from a thread that does not currently hold the GVL.
+unsafe fn with_gvl<F, R>(func: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    struct State<F, R> {
+        func: Option<F>,
+        result: Option<R>,
+    }
+
+    extern "C" fn call<F, R>(arg: *mut c_void) -> *mut c_void
+    where
+        F: FnOnce() -> R,
+    {
+        let state = unsafe { &mut *(arg as *mut State<F, R>) };
+        let func = state.func.take().unwrap();
+        state.result = Some(func());
+        std::ptr::null_mut()
+    }
+
+    let mut state = State {
+        func: Some(func),
+        result: None,
+    };
+    rb_sys::rb_thread_call_with_gvl(Some(call::<F, R>), &mut state as *mut _ as *mut c_void);
+    state.result.unwrap()
+}
+
+/// Builds a structured Ruby exception from a Wasm-originated trap, preserving
+/// the trap code (or WASI exit status) and the Wasm backtrace instead of
+/// flattening everything into an opaque message string. WASI exits become
+/// `Wasmtime::WasiExit`, all other traps `Wasmtime::Trap`.
+fn trap_error(trap: &wasmtime::Trap) -> Error {
+    let message = trap.to_string();
+
+    let (class, code) = match trap.i32_exit_status() {
+        Some(status) => (wasi_exit_class(), Some(status)),
+        None => (trap_class(), trap.trap_code().map(|c| c as i32)),
+    };
+
+    let exception = match class.funcall::<_, _, Value>("new", (message,)) {
+        Ok(exception) => exception,
+        Err(e) => return e,
+    };
+
+    if let Some(code) = code {
+        exception.ivar_set("@code", code).ok();
+    }
+
+    // `Trap::trace` returns `Option<&[FrameInfo]>` on the pinned wasmtime; a
+    // trap captured without a backtrace (or from outside a running store) has
+    // `None`, so flatten the option before mapping frames.
+    let frames: Vec<String> = trap
+        .trace()
+        .into_iter()
+        .flatten()
+        .map(|frame| frame.func_name().unwrap_or("<unknown>").to_string())
+        .collect();
+    exception.ivar_set("@wasm_backtrace", frames).ok();
+
+    match Exception::from_value(exception) {
+        Some(exception) => Error::from(exception),
+        None => error!("Could not build trap: {}", trap),
+    }
+}
+
+/// Re-raises the exception a host `Proc` raised (of any class, most usefully
+/// a `Wasmtime::Trap`/`WasiExit`), merging in the unwind frames captured on
+/// the wrapping wasmtime `Trap` as `@wasm_backtrace` if the exception doesn't
+/// already have its own. A Proc that
+/// raises one of these itself (rather than letting a guest trap produce it)
+/// never goes through `trap_error`, so without this its `wasm_backtrace` was
+/// always `nil` even though `trap` captured real frames at the point the
+/// Proc's raise unwound through the host call.
+fn merge_trap_trace(exception: Exception, trap: &wasmtime::Trap) -> Error {
+    let has_trace = matches!(
+        exception.ivar_get::<_, Value>("@wasm_backtrace"),
+        Ok(v) if !v.is_nil()
+    );
+    if !has_trace {
+        let frames: Vec<String> = trap
+            .trace()
+            .into_iter()
+            .flatten()
+            .map(|frame| frame.func_name().unwrap_or("<unknown>").to_string())
+            .collect();
+        exception.ivar_set("@wasm_backtrace", frames).ok();
+    }
+    Error::from(exception)
+}
+
+fn trap_class() -> RClass {
+    *memoize!(RClass: {
+        let class: RClass = root().const_get("Trap").unwrap();
+        gc::register_mark_object(class);
+        class
+    })
+}
+
+fn wasi_exit_class() -> RClass {
+    *memoize!(RClass: {
+        let class: RClass = root().const_get("WasiExit").unwrap();
+        gc::register_mark_object(class);
+        class
+    })
+}
+
+/// `Trap.new(message, code = nil)` — lets a host `Proc` raise a structured
+/// trap carrying an exit/trap `code`. The message is stored alongside so it
+/// survives the host→guest→host round trip without a custom `super` call.
+fn trap_initialize(rb_self: Value, args: &[Value]) -> Result<(), Error> {
+    let args = scan_args::<(), (Option<Value>, Option<Value>), (), (), (), ()>(args)?;
+    let (message, code) = args.optional;
+    if let Some(message) = message {
+        rb_self.ivar_set("@message", message)?;
+    }
+    if let Some(code) = code {
+        rb_self.ivar_set("@code", code)?;
+    }
+    Ok(())
+}
+
+fn trap_message(rb_self: Value) -> Result<Value, Error> {
+    // Mirror the default `Exception#message`: fall back to the class name
+    // when no message was given, so `Trap.new.message` (and `to_s`, aliased
+    // to this below, which Ruby's default exception printer calls) return a
+    // String instead of nil.
+    let message: Value = rb_self.ivar_get("@message")?;
+    if message.is_nil() {
+        rb_self.class().funcall("to_s", ())
+    } else {
+        Ok(message)
+    }
+}
+
+fn trap_code(rb_self: Value) -> Result<Value, Error> {
+    rb_self.ivar_get("@code")
+}
+
+fn trap_wasm_backtrace(rb_self: Value) -> Result<Value, Error> {
+    rb_self.ivar_get("@wasm_backtrace")
+}
+
+fn fiber_class() -> RClass {
+    *memoize!(RClass: {
+        let class: RClass = magnus::class::object()
+            .const_get("Fiber")
+            .expect("Fiber should be defined");
+        gc::register_mark_object(class);
+        class
+    })
+}
+
+// Asserts that the wrapped future is safe to move across threads. Like
+// `ShareableProc`, this holds only because the future is polled exclusively
+// while the GVL is held and never concurrently.
+#[repr(transparent)]
+struct SendFuture<F>(F);
+unsafe impl<F> Send for SendFuture<F> {}
+
+impl<F: Future> Future for SendFuture<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: we never move the inner future; this is structural pinning.
+        let inner = unsafe { self.map_unchecked_mut(|s| &mut s.0) };
+        inner.poll(cx)
     }
 }
 
@@ -216,36 +884,98 @@ impl From<BoxValue<Exception>> for InvokeError {
     }
 }
 
+/// Flips a `Caller`'s shared `valid` flag to `false` when dropped. Held by
+/// the trampoline closures in `make_func_callable`/`make_func_async_callable`
+/// so it drops -- invalidating every `Caller`/export Ruby code may have kept
+/// a reference to -- right before the `CallerImpl` they all borrow from goes
+/// out of scope.
+struct CallerValidityGuard(Rc<Cell<bool>>);
+
+impl Drop for CallerValidityGuard {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
+}
+
 struct Caller<'a> {
     inner: &'a RefCell<CallerImpl<'a, StoreData>>,
+    // The calling Store's own async flag (see `with_store_context`); fixed
+    // for the lifetime of this `Caller`, not something a nested call flips.
+    is_async: bool,
+    // Shared with every `Caller` exported from the same host call (see
+    // `export` below) and flipped to `false`, by the `CallerValidityGuard`
+    // living in the trampoline, right before `inner`'s real `CallerImpl`
+    // frame goes away. `inner`'s lifetime is erased by `unsafe impl
+    // TypedData` below, so nothing stops Ruby code from stashing this object
+    // (or an export pulled from it) past the host call returning; every
+    // method here must check `valid` before touching `inner`, or a stale
+    // reference used later is a dangling-reference dereference, not just a
+    // logic bug.
+    valid: Rc<Cell<bool>>,
 }
 
 impl<'a> Caller<'a> {
-    pub fn store_data(&self) -> Value {
-        self.inner.borrow().data().user_data()
+    fn check_valid(&self) -> Result<(), Error> {
+        if self.valid.get() {
+            Ok(())
+        } else {
+            Err(error!(
+                "Caller (or an export obtained from it) is no longer valid; \
+                 it only lives for the duration of the host call it was given to"
+            ))
+        }
     }
 
+    pub fn store_data(&self) -> Result<Value, Error> {
+        self.check_valid()?;
+        Ok(self.inner.borrow().data().user_data())
+    }
+
+    /// Look up an export of the instance that called this host function.
+    ///
+    /// The returned `Func`/`Memory`/`Global`/`Table` is bound to this live
+    /// `Caller` rather than to an owning `Store`, because the store is already
+    /// borrowed for the duration of the guest call and cannot be re-borrowed.
+    /// Each wrapper must resolve its context via [`with_store_context`] (as
+    /// `Func#call` does) so reads and writes go through the caller's borrow.
+    /// The binding is only valid for the duration of this host call; using it
+    /// (or a wrapper built from it) afterwards raises via `check_valid` rather
+    /// than dereferencing the by-then-dangling `CallerImpl`.
     pub fn export(&self, name: RString) -> Result<Option<Value>, Error> {
-        let mut caller_mut = self.inner.borrow_mut();
+        self.check_valid()?;
+        let export = {
+            let mut caller_mut = self.inner.borrow_mut();
+            match caller_mut.get_export(unsafe { name.as_str() }?) {
+                // `Extern` is `Copy`, so we can release the borrow before
+                // building the wrappers below.
+                Some(export) => export,
+                None => return Ok(None),
+            }
+        };
 
-        let ext = caller_mut
-            .get_export(unsafe { name.as_str() }?)
-            .map(|export| match export.ty(caller_mut.as_context_mut()) {
-                ExternType::Func(_func) => {
-                    todo!("Handle externs")
-                }
-                ExternType::Memory(_mem) => {
-                    todo!("Handle externs")
-                }
-                ExternType::Table(_table) => {
-                    todo!("Handle externs")
-                }
-                ExternType::Global(_global) => {
-                    todo!("Handle externs")
-                }
-            });
+        // Bind the wrappers to a fresh `Caller` over the same live context
+        // rather than to the user's store-data object: `Func#call` and the
+        // `Memory`/`Global`/`Table` accessors expect a context they can drive,
+        // which the attached user data is not. The binding is only valid for
+        // the duration of this host call, matching `Caller`'s own lifetime --
+        // sharing `valid` (rather than starting a fresh flag) means this
+        // export is invalidated right along with the `Caller` it came from.
+        let context = Value::from(Caller {
+            inner: self.inner,
+            is_async: self.is_async,
+            valid: self.valid.clone(),
+        });
+
+        let value = match export {
+            Extern::Func(func) => Value::from(Func::from_inner(context, func)),
+            Extern::Memory(mem) => Value::from(Memory::from_inner(context, mem)),
+            Extern::Global(global) => Value::from(Global::from_inner(context, global)),
+            Extern::Table(table) => Value::from(Table::from_inner(context, table)),
+            // Shared memories and other externs aren't surfaced to Ruby yet.
+            _ => return Ok(None),
+        };
 
-        Ok(ext)
+        Ok(Some(value))
     }
 }
 
@@ -263,6 +993,9 @@ unsafe impl<'a> TypedData for Caller<'a> {
     }
 }
 impl DataTypeFunctions for Caller<'_> {}
+// Safe under the same invariant as `ShareableProc`/`SendFuture`: a `Caller`
+// (including its `Rc<Cell<bool>>` validity flag, otherwise not `Send`) is
+// only ever touched while the GVL is held, one thread at a time.
 unsafe impl Send for Caller<'_> {}
 
 pub fn init() -> Result<(), Error> {
@@ -274,5 +1007,17 @@ pub fn init() -> Result<(), Error> {
     caller.define_method("store_data", method!(Caller::store_data, 0))?;
     caller.define_method("export", method!(Caller::export, 1))?;
 
+    // Structured traps: a guest trap (or a host `Proc` raising one of these)
+    // surfaces as a Ruby object carrying the trap `code` and `wasm_backtrace`
+    // rather than a flattened message string. `WasiExit` is the subclass raised
+    // for a WASI `proc_exit`, so embedders can tell an exit from a fault.
+    let trap = root().define_error("Trap", magnus::exception::standard_error())?;
+    trap.define_method("initialize", method!(trap_initialize, -1))?;
+    trap.define_method("message", method!(trap_message, 0))?;
+    trap.define_method("to_s", method!(trap_message, 0))?;
+    trap.define_method("code", method!(trap_code, 0))?;
+    trap.define_method("wasm_backtrace", method!(trap_wasm_backtrace, 0))?;
+    root().define_error("WasiExit", trap)?;
+
     Ok(())
 }